@@ -1,152 +1,549 @@
-use std::path::PathBuf;
-use std::process::{Child, Command, Stdio};
-use std::sync::Mutex;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::api::process::{Command, CommandChild, CommandEvent};
+use tauri::{AppHandle, Manager};
 
 use crate::keychain;
 
+/// Name of the bundled sidecar binary (see tauri.conf.json `externalBin`).
+const SIDECAR_NAME: &str = "narrativ-backend";
+
+/// Tauri event emitted for each line of backend output.
+const LOG_EVENT: &str = "backend://log";
+/// Tauri event emitted before each supervised restart attempt.
+const RESTART_EVENT: &str = "backend://restart";
+/// Tauri event emitted when the supervisor gives up restarting.
+const FAILED_EVENT: &str = "backend://failed";
+
+/// Rotate the backend log file once it grows past this size.
+const LOG_ROTATE_BYTES: u64 = 1024 * 1024;
+
+/// Base delay for restart backoff; doubles each attempt up to the cap.
+const RESTART_BACKOFF_BASE: Duration = Duration::from_millis(500);
+/// Upper bound for a single restart backoff delay.
+const RESTART_BACKOFF_CAP: Duration = Duration::from_secs(30);
+/// How many consecutive restarts to attempt before giving up.
+const MAX_RESTART_ATTEMPTS: u32 = 5;
+/// How long to wait for a graceful shutdown before escalating to a hard kill.
+const STOP_GRACE: Duration = Duration::from_secs(5);
+/// How far above the preferred port to scan for a free one before falling back
+/// to an OS-assigned ephemeral port.
+const PORT_SCAN_RANGE: u16 = 64;
+/// How long to wait for the backend to start accepting connections.
+const READINESS_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A single line of backend output, forwarded to the frontend console.
+#[derive(Clone, Serialize)]
+struct LogLine {
+    stream: &'static str,
+    line: String,
+}
+
+/// Payload for restart/failure events surfaced to the UI.
+#[derive(Clone, Serialize)]
+struct RestartEvent {
+    attempt: u32,
+    max_attempts: u32,
+    delay_ms: u64,
+}
+
+/// Current health of the supervised backend.
+#[derive(Clone, Serialize)]
+pub struct BackendHealth {
+    pub running: bool,
+    pub restart_count: u32,
+    pub port: u16,
+}
+
+/// Shared, thread-safe backend state owned jointly by the manager and the
+/// sidecar event loop.
+struct Inner {
+    /// Write/kill handle for the running sidecar child, if any.
+    child: Mutex<Option<CommandChild>>,
+    /// Liveness flag maintained by the sidecar event loop.
+    running: AtomicBool,
+    /// Preferred port requested at construction; the starting point for scans.
+    preferred_port: u16,
+    /// Port the backend is actually bound to, negotiated at each start.
+    port: AtomicU16,
+    /// App handle used to emit events; set once during app setup.
+    app: Mutex<Option<AppHandle>>,
+    /// Set while a stop/restart is in progress so a `Terminated` event isn't
+    /// treated as a crash.
+    intentional_stop: Mutex<bool>,
+    /// Number of automatic restarts since the last clean start.
+    restart_count: Mutex<u32>,
+}
+
 pub struct BackendManager {
-    process: Mutex<Option<Child>>,
-    port: u16,
+    inner: Arc<Inner>,
 }
 
 impl BackendManager {
     pub fn new(port: u16) -> Self {
         BackendManager {
-            process: Mutex::new(None),
-            port,
+            inner: Arc::new(Inner {
+                child: Mutex::new(None),
+                running: AtomicBool::new(false),
+                preferred_port: port,
+                port: AtomicU16::new(port),
+                app: Mutex::new(None),
+                intentional_stop: Mutex::new(false),
+                restart_count: Mutex::new(0),
+            }),
+        }
+    }
+
+    /// Register the Tauri app handle so the event loop can emit events.
+    pub fn set_app_handle(&self, app: AppHandle) {
+        if let Ok(mut guard) = self.inner.app.lock() {
+            *guard = Some(app);
         }
     }
 
     pub fn get_port(&self) -> u16 {
-        self.port
-    }
-
-    /// Find the bundled backend executable
-    fn find_bundled_backend() -> Option<PathBuf> {
-        // In production: look for bundled executable in Resources
-        if let Ok(exe_path) = std::env::current_exe() {
-            // macOS app bundle: .app/Contents/MacOS/app -> .app/Contents/Resources/
-            if let Some(parent) = exe_path.parent() {
-                let resources = parent.join("../Resources/narrativ-backend");
-                if resources.exists() {
-                    return Some(resources);
-                }
+        self.inner.port.load(Ordering::Relaxed)
+    }
+
+    /// Start the backend (sidecar, or Python fallback for dev).
+    pub fn start(&self, python_backend_path: &str) -> Result<(), String> {
+        // A user-initiated start resets the crash bookkeeping.
+        *self.inner.intentional_stop.lock().map_err(|e| e.to_string())? = false;
+        *self.inner.restart_count.lock().map_err(|e| e.to_string())? = 0;
+        self.inner.spawn(python_backend_path)
+    }
+
+    /// Stop the backend, asking it to flush before escalating to a hard kill.
+    pub fn stop(&self) -> Result<(), String> {
+        *self.inner.intentional_stop.lock().map_err(|e| e.to_string())? = true;
+        let child = self.inner.child.lock().map_err(|e| e.to_string())?.take();
+        if let Some(child) = child {
+            self.inner.terminate(child);
+        }
+        self.inner.running.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Whether the backend is currently running, per the sidecar event stream.
+    pub fn is_running(&self) -> bool {
+        self.inner.running.load(Ordering::Relaxed)
+    }
+
+    /// Current backend health, including the automatic restart count.
+    pub fn health(&self) -> BackendHealth {
+        BackendHealth {
+            running: self.is_running(),
+            restart_count: self.inner.restart_count.lock().map(|c| *c).unwrap_or(0),
+            port: self.inner.port.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Restart the backend
+    pub fn restart(&self, python_backend_path: &str) -> Result<(), String> {
+        self.stop()?;
+        // Small delay to ensure port is released
+        std::thread::sleep(Duration::from_millis(500));
+        self.start(python_backend_path)
+    }
+}
+
+impl Drop for BackendManager {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+impl Inner {
+    /// Build the sidecar command, falling back to a discovered Python
+    /// interpreter running `main.py` when the sidecar isn't bundled.
+    fn build_command(
+        &self,
+        python_backend_path: &str,
+        port: u16,
+        secrets: &HashMap<String, String>,
+    ) -> Result<Command, String> {
+        let mut command = match Command::new_sidecar(SIDECAR_NAME) {
+            Ok(sidecar) => {
+                println!("Using bundled sidecar backend: {}", SIDECAR_NAME);
+                sidecar
             }
+            Err(_) => {
+                // Fallback to a discovered Python interpreter for development.
+                let python = resolve_python(python_backend_path)?;
+                println!(
+                    "Using Python backend at: {:?} (interpreter: {:?})",
+                    python_backend_path, python
+                );
+                let main_py = format!("{}/main.py", python_backend_path);
+                Command::new(python.to_string_lossy().into_owned()).args([main_py])
+            }
+        };
+
+        let mut env = HashMap::new();
+        env.insert("NARRATIV_PORT".to_string(), port.to_string());
+        for (name, value) in secrets {
+            env.insert(name.to_uppercase(), value.clone());
+        }
+        command = command.envs(env);
+        Ok(command)
+    }
+
+    /// Spawn the sidecar and an event loop that forwards output and restarts
+    /// the child if it terminates unexpectedly.
+    fn spawn(self: &Arc<Self>, python_backend_path: &str) -> Result<(), String> {
+        if self.running.load(Ordering::Relaxed) {
+            return Err("Backend is already running".to_string());
         }
 
-        // Development: look in src-tauri/resources
-        let dev_paths = vec![
-            PathBuf::from("resources/narrativ-backend"),
-            PathBuf::from("src-tauri/resources/narrativ-backend"),
-            PathBuf::from("../resources/narrativ-backend"),
-        ];
+        // Negotiate a free port so a second instance (or anything already on
+        // the preferred port) doesn't collide silently.
+        let port = pick_port(self.preferred_port)?;
+        self.port.store(port, Ordering::Relaxed);
+
+        // Hand the backend only the active vault's declared keys, each exposed
+        // as an uppercased environment variable (e.g. google_api_key ->
+        // GOOGLE_API_KEY).
+        let secrets = keychain::retrieve_all()?;
+        let command = self.build_command(python_backend_path, port, &secrets)?;
+
+        let (rx, child) = command
+            .spawn()
+            .map_err(|e| format!("Failed to start backend: {}", e))?;
+
+        *self.child.lock().map_err(|e| e.to_string())? = Some(child);
+        self.running.store(true, Ordering::Relaxed);
 
-        for path in dev_paths {
-            if path.exists() {
-                return Some(path);
+        // Consume the typed event stream on a dedicated thread.
+        let inner = Arc::clone(self);
+        let path = python_backend_path.to_string();
+        std::thread::spawn(move || inner.run_event_loop(rx, path));
+
+        // Only report success once the backend is actually accepting connections.
+        if !wait_until_ready(port, READINESS_TIMEOUT) {
+            // Don't leave an unresponsive child running and `running` set, or
+            // health would report a started-but-failed backend and block the
+            // next `start()`. Mark the teardown intentional so the event loop
+            // doesn't treat it as a crash and restart, then tear it down before
+            // surfacing the error.
+            if let Ok(mut stop) = self.intentional_stop.lock() {
+                *stop = true;
+            }
+            if let Ok(mut guard) = self.child.lock() {
+                if let Some(child) = guard.take() {
+                    self.terminate(child);
+                }
             }
+            self.running.store(false, Ordering::Relaxed);
+            return Err(format!(
+                "Backend did not become ready on port {} within {:?}",
+                port, READINESS_TIMEOUT
+            ));
         }
 
-        None
+        // The child recovered; clear the crash tally so only *consecutive*
+        // failed restarts count toward the give-up threshold rather than every
+        // crash over the session's lifetime.
+        if let Ok(mut count) = self.restart_count.lock() {
+            *count = 0;
+        }
+
+        Ok(())
     }
 
-    /// Start the backend process (bundled or Python fallback for dev)
-    pub fn start(&self, python_backend_path: &str) -> Result<(), String> {
-        // Check if already running
-        {
-            let process = self.process.lock().map_err(|e| e.to_string())?;
-            if process.is_some() {
-                return Err("Backend is already running".to_string());
+    /// Drain the sidecar event stream: forward output and react to termination.
+    fn run_event_loop(self: Arc<Self>, mut rx: tauri::async_runtime::Receiver<CommandEvent>, path: String) {
+        while let Some(event) = rx.blocking_recv() {
+            match event {
+                CommandEvent::Stdout(line) => self.forward("stdout", line),
+                CommandEvent::Stderr(line) => self.forward("stderr", line),
+                CommandEvent::Error(err) => log::error!("[backend] error: {}", err),
+                CommandEvent::Terminated(payload) => {
+                    self.running.store(false, Ordering::Relaxed);
+                    self.handle_terminated(payload.code, &path);
+                    break;
+                }
+                _ => {}
             }
         }
+    }
 
-        // Get API keys from keychain
-        let google_key = keychain::retrieve_api_key("google_api_key")?
-            .unwrap_or_default();
-        let tavily_key = keychain::retrieve_api_key("tavily_api_key")?
-            .unwrap_or_default();
-        let fal_key = keychain::retrieve_api_key("fal_api_key")?
-            .unwrap_or_default();
-
-        // Try bundled executable first, then fall back to Python for development
-        let child = if let Some(bundled_path) = Self::find_bundled_backend() {
-            println!("Using bundled backend: {:?}", bundled_path);
-            Command::new(&bundled_path)
-                .env("NARRATIV_PORT", self.port.to_string())
-                .env("GOOGLE_API_KEY", &google_key)
-                .env("TAVILY_API_KEY", &tavily_key)
-                .env("FAL_API_KEY", &fal_key)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-                .map_err(|e| format!("Failed to start bundled backend: {}", e))?
+    /// Forward one line of backend output to the log, the UI, and the log file.
+    fn forward(&self, stream: &'static str, line: String) {
+        if stream == "stderr" {
+            log::error!("[backend] {}", line);
         } else {
-            // Fallback to Python for development
-            println!("Using Python backend at: {:?}", python_backend_path);
-            let main_py = format!("{}/main.py", python_backend_path);
-            Command::new("python3")
-                .arg(&main_py)
-                .env("NARRATIV_PORT", self.port.to_string())
-                .env("GOOGLE_API_KEY", &google_key)
-                .env("TAVILY_API_KEY", &tavily_key)
-                .env("FAL_API_KEY", &fal_key)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-                .map_err(|e| format!("Failed to start Python backend: {}", e))?
+            log::info!("[backend] {}", line);
+        }
+
+        if let Some(app) = self.app.lock().ok().and_then(|a| a.clone()) {
+            let _ = app.emit_all(
+                LOG_EVENT,
+                LogLine {
+                    stream,
+                    line: line.clone(),
+                },
+            );
+        }
+
+        if file_logging_enabled() {
+            let _ = tee_to_file(stream, &line);
+        }
+    }
+
+    /// React to the sidecar terminating: restart with backoff on an unexpected
+    /// exit, unless a stop was requested or it exited cleanly.
+    fn handle_terminated(self: &Arc<Self>, code: Option<i32>, python_backend_path: &str) {
+        if self.stop_requested() || code == Some(0) {
+            return;
+        }
+
+        let attempt = {
+            let mut count = match self.restart_count.lock() {
+                Ok(c) => c,
+                Err(_) => return,
+            };
+            *count += 1;
+            *count
         };
 
-        *self.process.lock().map_err(|e| e.to_string())? = Some(child);
+        if attempt > MAX_RESTART_ATTEMPTS {
+            log::error!(
+                "[backend] exited with {:?}; giving up after {} restart attempts",
+                code,
+                MAX_RESTART_ATTEMPTS
+            );
+            self.emit(
+                FAILED_EVENT,
+                RestartEvent {
+                    attempt,
+                    max_attempts: MAX_RESTART_ATTEMPTS,
+                    delay_ms: 0,
+                },
+            );
+            return;
+        }
 
-        Ok(())
+        let delay = backoff(attempt);
+        log::error!(
+            "[backend] exited with {:?}; restart attempt {}/{} in {:?}",
+            code,
+            attempt,
+            MAX_RESTART_ATTEMPTS,
+            delay
+        );
+        self.emit(
+            RESTART_EVENT,
+            RestartEvent {
+                attempt,
+                max_attempts: MAX_RESTART_ATTEMPTS,
+                delay_ms: delay.as_millis() as u64,
+            },
+        );
+
+        std::thread::sleep(delay);
+        if self.stop_requested() {
+            return;
+        }
+
+        if let Err(e) = self.spawn(python_backend_path) {
+            log::error!("[backend] restart failed: {}", e);
+        }
     }
 
-    /// Stop the Python backend process
-    pub fn stop(&self) -> Result<(), String> {
-        let mut process = self.process.lock().map_err(|e| e.to_string())?;
+    /// Terminate `child` gracefully, escalating to a hard kill after the grace
+    /// period. On Unix this sends SIGTERM first so the backend can flush state.
+    fn terminate(&self, child: CommandChild) {
+        #[cfg(unix)]
+        {
+            // SAFETY: `child.pid()` is this process's own sidecar child.
+            unsafe {
+                libc::kill(child.pid() as libc::pid_t, libc::SIGTERM);
+            }
 
-        if let Some(mut child) = process.take() {
-            child
-                .kill()
-                .map_err(|e| format!("Failed to kill backend: {}", e))?;
-            let _ = child.wait();
+            let start = Instant::now();
+            while start.elapsed() < STOP_GRACE {
+                if !self.running.load(Ordering::Relaxed) {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
         }
 
-        Ok(())
+        // Windows, or a Unix child that ignored SIGTERM: terminate hard.
+        let _ = child.kill();
     }
 
-    /// Check if the backend is running
-    pub fn is_running(&self) -> bool {
-        let process = match self.process.lock() {
-            Ok(p) => p,
-            Err(_) => return false,
+    fn stop_requested(&self) -> bool {
+        self.intentional_stop.lock().map(|s| *s).unwrap_or(false)
+    }
+
+    /// Emit a Tauri event if an app handle has been registered.
+    fn emit(&self, event: &str, payload: RestartEvent) {
+        if let Some(app) = self.app.lock().ok().and_then(|a| a.clone()) {
+            let _ = app.emit_all(event, payload);
+        }
+    }
+}
+
+/// Pick a free localhost port, scanning upward from `preferred` and falling
+/// back to an OS-assigned ephemeral port if the scan range is exhausted.
+fn pick_port(preferred: u16) -> Result<u16, String> {
+    for offset in 0..PORT_SCAN_RANGE {
+        let port = match preferred.checked_add(offset) {
+            Some(p) => p,
+            None => break,
         };
+        if TcpListener::bind(("127.0.0.1", port)).is_ok() {
+            return Ok(port);
+        }
+    }
+
+    // Nothing free near the preferred port; let the OS assign one.
+    TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|l| l.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| format!("Could not find a free port: {}", e))
+}
+
+/// Poll localhost until the backend accepts a connection on `port` or `timeout`.
+fn wait_until_ready(port: u16, timeout: Duration) -> bool {
+    let addr: std::net::SocketAddr = ([127, 0, 0, 1], port).into();
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if TcpStream::connect_timeout(&addr, Duration::from_millis(500)).is_ok() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    false
+}
 
-        if let Some(ref child) = *process {
-            // Check if process is still alive by trying to get status
-            match Command::new("kill")
-                .args(["-0", &child.id().to_string()])
-                .status()
-            {
-                Ok(status) => status.success(),
-                Err(_) => false,
+/// Resolve a usable Python 3 interpreter for the dev fallback.
+///
+/// Tries, in order: the `NARRATIV_PYTHON` override, a project-local virtualenv,
+/// then `python3`/`python` found on `PATH`. Each candidate is validated by
+/// running `--version`. Returns an error listing everything tried when none is
+/// a working Python 3.
+fn resolve_python(python_backend_path: &str) -> Result<PathBuf, String> {
+    let mut tried: Vec<String> = Vec::new();
+    let mut consider = |candidate: PathBuf, tried: &mut Vec<String>| -> Option<PathBuf> {
+        let shown = candidate.display().to_string();
+        if is_python3(&candidate) {
+            Some(candidate)
+        } else {
+            tried.push(shown);
+            None
+        }
+    };
+
+    if let Ok(override_path) = std::env::var("NARRATIV_PYTHON") {
+        if let Some(python) = consider(PathBuf::from(override_path), &mut tried) {
+            return Ok(python);
+        }
+    }
+
+    let venv = Path::new(python_backend_path).join(venv_python_rel());
+    if let Some(python) = consider(venv, &mut tried) {
+        return Ok(python);
+    }
+
+    for name in ["python3", "python"] {
+        if let Some(found) = which(name) {
+            if let Some(python) = consider(found, &mut tried) {
+                return Ok(python);
             }
         } else {
-            false
+            tried.push(format!("{} (not on PATH)", name));
         }
     }
 
-    /// Restart the backend
-    pub fn restart(&self, python_backend_path: &str) -> Result<(), String> {
-        self.stop()?;
-        // Small delay to ensure port is released
-        std::thread::sleep(std::time::Duration::from_millis(500));
-        self.start(python_backend_path)
+    Err(format!(
+        "No usable Python 3 interpreter found. Tried: {}. \
+         Set NARRATIV_PYTHON to a Python 3 executable.",
+        tried.join(", ")
+    ))
+}
+
+/// Relative path to a project-local virtualenv interpreter.
+fn venv_python_rel() -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(".venv").join("Scripts").join("python.exe")
+    } else {
+        PathBuf::from(".venv").join("bin").join("python")
     }
 }
 
-impl Drop for BackendManager {
-    fn drop(&mut self) {
-        let _ = self.stop();
+/// Whether `path` runs and reports itself as Python 3.x.
+fn is_python3(path: &Path) -> bool {
+    let output = match std::process::Command::new(path).arg("--version").output() {
+        Ok(o) => o,
+        Err(_) => return false,
+    };
+    // Older Pythons print the version banner to stderr, newer ones to stdout.
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    text.trim().starts_with("Python 3")
+}
+
+/// Minimal PATH search for an executable, modeled on the `which` crate.
+fn which(name: &str) -> Option<PathBuf> {
+    let path_var = std::env::var_os("PATH")?;
+    for dir in std::env::split_paths(&path_var) {
+        let candidate = dir.join(name);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        if cfg!(windows) {
+            let exe = dir.join(format!("{}.exe", name));
+            if exe.is_file() {
+                return Some(exe);
+            }
+        }
     }
+    None
+}
+
+/// Exponential backoff delay for restart `attempt` (1-based), capped.
+fn backoff(attempt: u32) -> Duration {
+    let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+    RESTART_BACKOFF_BASE
+        .saturating_mul(factor)
+        .min(RESTART_BACKOFF_CAP)
+}
+
+/// Whether backend output is tee'd to the rotating log file. On by default;
+/// set `NARRATIV_BACKEND_LOG` to `0`/`false`/`off` to opt out.
+fn file_logging_enabled() -> bool {
+    match std::env::var("NARRATIV_BACKEND_LOG") {
+        Ok(v) => !matches!(v.trim().to_ascii_lowercase().as_str(), "0" | "false" | "off"),
+        Err(_) => true,
+    }
+}
+
+/// Append a line to the rotating backend log file under the app data dir.
+fn tee_to_file(stream: &str, line: &str) -> std::io::Result<()> {
+    let dir = match dirs::data_dir() {
+        Some(d) => d.join("com.narrativ.app").join("logs"),
+        None => return Ok(()),
+    };
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("backend.log");
+
+    // Rotate once the current log grows past the threshold.
+    if let Ok(meta) = fs::metadata(&path) {
+        if meta.len() >= LOG_ROTATE_BYTES {
+            let _ = fs::rename(&path, dir.join("backend.log.1"));
+        }
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "[{}] {}", stream, line)
 }
@@ -1,4 +1,4 @@
-use crate::backend::BackendManager;
+use crate::backend::{BackendHealth, BackendManager};
 use crate::keychain;
 use crate::vault;
 use serde::{Deserialize, Serialize};
@@ -43,14 +43,51 @@ pub fn delete_api_key(service: String) -> Result<(), String> {
     keychain::delete_api_key(&service)
 }
 
+/// Unlock the vault by deriving the master key from the user's passphrase.
+///
+/// Now that the session holds a key, the backend (which needs the vault's
+/// secrets) can start; kick it off if the setup hook deferred the spawn.
+#[tauri::command]
+pub fn unlock_vault(
+    app_handle: tauri::AppHandle,
+    backend_manager: State<'_, Mutex<BackendManager>>,
+    passphrase: String,
+) -> Result<(), String> {
+    keychain::unlock_session(&passphrase)?;
+
+    if let Ok(manager) = backend_manager.lock() {
+        if !manager.is_running() {
+            if let Ok(path) = get_python_backend_path(app_handle) {
+                if let Err(e) = manager.start(&path) {
+                    eprintln!("Failed to start backend after unlock: {}", e);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Lock the vault, dropping the derived key from memory
+#[tauri::command]
+pub fn lock_vault() -> Result<(), String> {
+    keychain::lock_session()
+}
+
+/// Change the vault passphrase, re-encrypting all secrets and vault files
+#[tauri::command]
+pub fn change_passphrase(old_passphrase: String, new_passphrase: String) -> Result<(), String> {
+    keychain::change_passphrase(&old_passphrase, &new_passphrase)
+}
+
 /// Check which API keys are configured
 #[tauri::command]
 pub fn get_api_key_status() -> Vec<ApiKeyStatus> {
     keychain::get_stored_key_names()
-        .iter()
-        .map(|name| ApiKeyStatus {
-            name: name.to_string(),
-            configured: keychain::has_api_key(name),
+        .into_iter()
+        .map(|name| {
+            let configured = keychain::has_api_key(&name);
+            ApiKeyStatus { name, configured }
         })
         .collect()
 }
@@ -99,6 +136,19 @@ pub fn get_backend_status(backend_manager: State<'_, Mutex<BackendManager>>) ->
     }
 }
 
+/// Get the backend health, including the automatic restart count
+#[tauri::command]
+pub fn get_backend_health(backend_manager: State<'_, Mutex<BackendManager>>) -> BackendHealth {
+    match backend_manager.lock() {
+        Ok(m) => m.health(),
+        Err(_) => BackendHealth {
+            running: false,
+            restart_count: 0,
+            port: 0,
+        },
+    }
+}
+
 /// Get the backend port
 #[tauri::command]
 pub fn get_backend_port(backend_manager: State<'_, Mutex<BackendManager>>) -> u16 {
@@ -234,3 +284,57 @@ pub fn add_vault_to_history(name: String, path: String) -> Result<(), String> {
 pub fn remove_vault_from_history(path: String) -> Result<(), String> {
     vault::remove_from_vault_history(&path)
 }
+
+/// Write a file into the active vault through the at-rest encryption layer.
+///
+/// Note: only the Tauri frontend routes writes through this command. The
+/// Python backend is a separate process and writes research/ and attachments/
+/// directly as plaintext — encrypting its own output is an explicit non-goal
+/// for now. `change_passphrase` therefore skips any file it can't open under
+/// the session key (see `keychain::change_passphrase`).
+#[tauri::command]
+pub fn write_vault_file(rel_path: String, contents: Vec<u8>) -> Result<(), String> {
+    let path = vault::load_vault_path().ok_or_else(|| "No vault is configured".to_string())?;
+    let vault = vault::Vault::open(std::path::Path::new(&path))?;
+    vault.write_encrypted(&rel_path, &contents)
+}
+
+/// Read a file from the active vault through the at-rest encryption layer
+#[tauri::command]
+pub fn read_vault_file(rel_path: String) -> Result<Vec<u8>, String> {
+    let path = vault::load_vault_path().ok_or_else(|| "No vault is configured".to_string())?;
+    let vault = vault::Vault::open(std::path::Path::new(&path))?;
+    vault.read_encrypted(&rel_path)
+}
+
+/// Encrypt an existing vault's research/, attachments/ and styles/ trees in place
+#[tauri::command]
+pub fn encrypt_existing_vault() -> Result<(), String> {
+    let path = vault::load_vault_path().ok_or_else(|| "No vault is configured".to_string())?;
+    let vault = vault::Vault::open(std::path::Path::new(&path))?;
+    vault.encrypt_existing()
+}
+
+/// Write an encrypted snapshot of the active vault to `dest_path`
+#[tauri::command]
+pub fn create_vault_snapshot(dest_path: String) -> Result<(), String> {
+    let path = vault::load_vault_path().ok_or_else(|| "No vault is configured".to_string())?;
+    let vault = vault::Vault::open(std::path::Path::new(&path))?;
+    vault::snapshot::create(&vault, std::path::Path::new(&dest_path))
+}
+
+/// Restore an encrypted snapshot into `dest_path` using `passphrase`
+#[tauri::command]
+pub fn restore_vault_snapshot(
+    archive_path: String,
+    dest_path: String,
+    passphrase: String,
+    force: bool,
+) -> Result<(), String> {
+    vault::snapshot::restore(
+        std::path::Path::new(&archive_path),
+        std::path::Path::new(&dest_path),
+        &passphrase,
+        force,
+    )
+}
@@ -2,33 +2,298 @@ use aes_gcm::{
     aead::{Aead, KeyInit},
     Aes256Gcm, Nonce,
 };
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use rand::Rng;
-use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Mutex;
 
 const NONCE_SIZE: usize = 12;
-const APP_SALT: &[u8] = b"revelio_secrets_v2_stable";
+const SALT_SIZE: usize = 16;
 
-/// Derive encryption key from user's home directory path (consistent across builds)
-fn get_encryption_key() -> [u8; 32] {
-    // Use home directory as the unique identifier - consistent across app versions
-    let home_dir = dirs::home_dir()
-        .map(|p| p.to_string_lossy().to_string())
-        .unwrap_or_else(|| "default_user".to_string());
+// Argon2id parameters: 64 MiB of memory, 3 iterations, single lane.
+const KDF_MEM_KIB: u32 = 64 * 1024;
+const KDF_ITERATIONS: u32 = 3;
+const KDF_PARALLELISM: u32 = 1;
 
-    let mut hasher = Sha256::new();
-    hasher.update(home_dir.as_bytes());
-    hasher.update(APP_SALT);
-    hasher.finalize().into()
+/// The master key derived from the user's passphrase, held in memory for the
+/// duration of an unlocked session. Cleared on `lock_session`.
+static SESSION_KEY: Mutex<Option<[u8; 32]>> = Mutex::new(None);
+
+/// Derive the 32-byte vault key from a passphrase and salt using Argon2id.
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], String> {
+    let params = Params::new(KDF_MEM_KIB, KDF_ITERATIONS, KDF_PARALLELISM, Some(32))
+        .map_err(|e| format!("Invalid KDF parameters: {}", e))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+    let mut key = [0u8; 32];
+    argon2
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+/// Open the currently configured vault, failing if none is set.
+fn active_vault() -> Result<crate::vault::Vault, String> {
+    let path = crate::vault::load_vault_path()
+        .ok_or_else(|| "No vault is configured".to_string())?;
+    crate::vault::Vault::open(std::path::Path::new(&path))
+}
+
+/// Decode the stored KDF salt, generating and persisting a fresh one (plus a
+/// fresh verifier under the derived key) on first unlock of a vault.
+fn resolve_salt_and_verify(
+    vault: &crate::vault::Vault,
+    passphrase: &str,
+) -> Result<[u8; 32], String> {
+    let mut config = vault.load_config()?;
+
+    match (&config.kdf_salt, &config.verifier) {
+        (Some(salt_b64), Some(verifier)) => {
+            let salt = BASE64
+                .decode(salt_b64)
+                .map_err(|_| "corrupt vault".to_string())?;
+            let key = derive_key(passphrase, &salt)?;
+            // A format error means the blob is damaged; an AEAD failure means
+            // the key (hence passphrase) is wrong.
+            let blob = BASE64
+                .decode(verifier)
+                .map_err(|_| "corrupt vault".to_string())?;
+            if blob.len() < NONCE_SIZE {
+                return Err("corrupt vault".to_string());
+            }
+            match unseal(&blob, &key) {
+                Ok(plain) if plain.as_slice() == crate::vault::VERIFIER_PLAINTEXT.as_bytes() => Ok(key),
+                Ok(_) => Err("corrupt vault".to_string()),
+                Err(_) => Err("incorrect passphrase".to_string()),
+            }
+        }
+        _ => {
+            // First unlock: establish the salt and verifier from this passphrase.
+            let mut salt = [0u8; SALT_SIZE];
+            rand::thread_rng().fill(&mut salt);
+            let key = derive_key(passphrase, &salt)?;
+            let verifier = encrypt(crate::vault::VERIFIER_PLAINTEXT, &key)?;
+            config.kdf_salt = Some(BASE64.encode(salt));
+            config.verifier = Some(verifier);
+            vault.save_config(&config)?;
+            Ok(key)
+        }
+    }
+}
+
+/// Unlock the session by deriving the master key from the passphrase and
+/// holding it in memory until `lock_session` is called. Returns a distinct
+/// error for an incorrect passphrase versus a corrupt vault.
+pub fn unlock_session(passphrase: &str) -> Result<(), String> {
+    let vault = active_vault()?;
+    let key = resolve_salt_and_verify(&vault, passphrase)?;
+    *SESSION_KEY.lock().map_err(|e| e.to_string())? = Some(key);
+    Ok(())
+}
+
+/// Temp-file sibling path for an atomic write to `path`.
+fn temp_path(path: &std::path::Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}.narrativ-tmp", name))
+}
+
+/// Backup sibling path used to stash an original during the commit phase so a
+/// failed rename can be rolled back.
+fn backup_path(path: &std::path::Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}.narrativ-bak", name))
+}
+
+/// Re-key the vault: verify `old` against the stored verifier, re-encrypt all
+/// secrets and (if enabled) every vault file under a freshly derived key, and
+/// swap in a new salt/verifier. Each file is staged to a temp file and renamed
+/// into place with its original kept aside until the new salt/verifier is
+/// saved, so an error at any step rolls back to the old-key originals. The
+/// renames are not atomic as a group: a crash during the commit, or before the
+/// config is saved, can leave files under different keys with their
+/// `.narrativ-bak` originals beside them.
+pub fn change_passphrase(old: &str, new: &str) -> Result<(), String> {
+    let vault = active_vault()?;
+    let mut config = vault.load_config()?;
+
+    let salt_b64 = config
+        .kdf_salt
+        .clone()
+        .ok_or_else(|| "Vault has no passphrase set".to_string())?;
+    let verifier = config
+        .verifier
+        .clone()
+        .ok_or_else(|| "Vault has no passphrase set".to_string())?;
+
+    let old_salt = BASE64.decode(&salt_b64).map_err(|_| "corrupt vault".to_string())?;
+    let old_key = derive_key(old, &old_salt)?;
+    match decrypt(&verifier, &old_key) {
+        Ok(ref plain) if plain == crate::vault::VERIFIER_PLAINTEXT => {}
+        Ok(_) => return Err("corrupt vault".to_string()),
+        Err(_) => return Err("incorrect passphrase".to_string()),
+    }
+
+    // Derive the replacement key from a fresh salt.
+    let mut new_salt = [0u8; SALT_SIZE];
+    rand::thread_rng().fill(&mut new_salt);
+    let new_key = derive_key(new, &new_salt)?;
+
+    // Decrypt everything up front so a bad read aborts before any write.
+    let secrets_file = secrets_path()?;
+    let secrets = if secrets_file.exists() {
+        read_secrets(&old_key)?
+    } else {
+        HashMap::new()
+    };
+
+    let content_files = if config.encrypted {
+        vault.content_files()
+    } else {
+        Vec::new()
+    };
+    let mut decrypted: Vec<(PathBuf, Vec<u8>)> = Vec::with_capacity(content_files.len());
+    for path in &content_files {
+        let sealed = fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        // Files written directly by the backend (which doesn't round-trip
+        // through `write_encrypted`) may still be plaintext. Leave anything we
+        // can't open with the old key untouched rather than aborting rotation.
+        match unseal(&sealed, &old_key) {
+            Ok(plain) => decrypted.push((path.clone(), plain)),
+            Err(_) => log::warn!("[keychain] skipping non-sealed vault file during rotation: {:?}", path),
+        }
+    }
+
+    // Re-encrypt to temp files; on any failure remove them and leave originals.
+    let mut temps: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let rollback = |temps: &[(PathBuf, PathBuf)]| {
+        for (tmp, _) in temps {
+            let _ = fs::remove_file(tmp);
+        }
+    };
+
+    if secrets_file.exists() || !secrets.is_empty() {
+        let json = serde_json::to_string_pretty(&secrets).map_err(|e| e.to_string())?;
+        let sealed = encrypt(&json, &new_key)?;
+        let tmp = temp_path(&secrets_file);
+        if let Err(e) = fs::write(&tmp, sealed) {
+            rollback(&temps);
+            return Err(format!("Failed to stage secrets: {}", e));
+        }
+        temps.push((tmp, secrets_file.clone()));
+    }
+
+    for (path, plain) in &decrypted {
+        let sealed = match seal(plain, &new_key) {
+            Ok(s) => s,
+            Err(e) => {
+                rollback(&temps);
+                return Err(e);
+            }
+        };
+        let tmp = temp_path(path);
+        if let Err(e) = fs::write(&tmp, sealed) {
+            rollback(&temps);
+            return Err(format!("Failed to stage {:?}: {}", path, e));
+        }
+        temps.push((tmp, path.clone()));
+    }
+
+    // Commit: rename every staged file into place, first moving the original
+    // aside so any failure can restore the old-key state. The vault must never
+    // be left mixed-key.
+    let commit_rollback = |committed: &[(PathBuf, PathBuf, bool)]| {
+        for (final_path, backup, had_original) in committed {
+            if *had_original {
+                let _ = fs::rename(backup, final_path);
+            } else {
+                let _ = fs::remove_file(final_path);
+            }
+        }
+    };
+
+    let mut committed: Vec<(PathBuf, PathBuf, bool)> = Vec::with_capacity(temps.len());
+    for (tmp, final_path) in &temps {
+        let backup = backup_path(final_path);
+        let had_original = final_path.exists();
+        if had_original {
+            if let Err(e) = fs::rename(final_path, &backup) {
+                commit_rollback(&committed);
+                rollback(&temps);
+                return Err(format!("Failed to back up {:?}: {}", final_path, e));
+            }
+        }
+        if let Err(e) = fs::rename(tmp, final_path) {
+            if had_original {
+                let _ = fs::rename(&backup, final_path);
+            }
+            commit_rollback(&committed);
+            rollback(&temps);
+            return Err(format!("Failed to commit {:?}: {}", final_path, e));
+        }
+        committed.push((final_path.clone(), backup, had_original));
+    }
+
+    // Update the passphrase metadata and adopt the new key for this session.
+    // If this fails the new-key files are undecryptable with either key, so
+    // roll the renames back to the old-key originals first.
+    config.kdf_salt = Some(BASE64.encode(new_salt));
+    config.verifier = match encrypt(crate::vault::VERIFIER_PLAINTEXT, &new_key) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            commit_rollback(&committed);
+            return Err(e);
+        }
+    };
+    if let Err(e) = vault.save_config(&config) {
+        commit_rollback(&committed);
+        return Err(e);
+    }
+
+    // Metadata is committed; the backups are now redundant.
+    for (_, backup, had_original) in &committed {
+        if *had_original {
+            let _ = fs::remove_file(backup);
+        }
+    }
+
+    *SESSION_KEY.lock().map_err(|e| e.to_string())? = Some(new_key);
+    Ok(())
+}
+
+/// Drop the in-memory master key, re-locking the vault.
+pub fn lock_session() -> Result<(), String> {
+    *SESSION_KEY.lock().map_err(|e| e.to_string())? = None;
+    Ok(())
+}
+
+/// Whether the session currently holds a derived key.
+pub fn is_unlocked() -> bool {
+    SESSION_KEY
+        .lock()
+        .map(|k| k.is_some())
+        .unwrap_or(false)
 }
 
-/// Encrypt data using AES-256-GCM
-fn encrypt(plaintext: &str) -> Result<String, String> {
-    let key = get_encryption_key();
-    let cipher = Aes256Gcm::new_from_slice(&key).map_err(|e| e.to_string())?;
+/// Fetch the current session key, failing if the vault has not been unlocked.
+fn session_key() -> Result<[u8; 32], String> {
+    SESSION_KEY
+        .lock()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Vault is locked - unlock with your passphrase first".to_string())
+}
+
+/// Seal bytes with AES-256-GCM under `key`, prepending a random 12-byte nonce.
+fn seal(plaintext: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, String> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
 
     // Generate random nonce
     let mut rng = rand::thread_rng();
@@ -37,94 +302,163 @@ fn encrypt(plaintext: &str) -> Result<String, String> {
     let nonce = Nonce::from_slice(&nonce_bytes);
 
     let ciphertext = cipher
-        .encrypt(nonce, plaintext.as_bytes())
+        .encrypt(nonce, plaintext)
         .map_err(|e| e.to_string())?;
 
-    // Prepend nonce to ciphertext and encode as base64
+    // Prepend nonce to ciphertext
     let mut combined = nonce_bytes.to_vec();
     combined.extend(ciphertext);
-    Ok(BASE64.encode(combined))
+    Ok(combined)
 }
 
-/// Decrypt data using AES-256-GCM
-fn decrypt(encrypted: &str) -> Result<String, String> {
-    let key = get_encryption_key();
-    let cipher = Aes256Gcm::new_from_slice(&key).map_err(|e| e.to_string())?;
-
-    let combined = BASE64.decode(encrypted).map_err(|e| e.to_string())?;
+/// Open a nonce-prepended AES-256-GCM blob produced by `seal`.
+fn unseal(data: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, String> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
 
-    if combined.len() < NONCE_SIZE {
+    if data.len() < NONCE_SIZE {
         return Err("Invalid encrypted data".to_string());
     }
 
-    let (nonce_bytes, ciphertext) = combined.split_at(NONCE_SIZE);
+    let (nonce_bytes, ciphertext) = data.split_at(NONCE_SIZE);
     let nonce = Nonce::from_slice(nonce_bytes);
 
-    let plaintext = cipher
+    cipher
         .decrypt(nonce, ciphertext)
-        .map_err(|_| "Decryption failed - keys may need to be re-entered".to_string())?;
+        .map_err(|_| "Decryption failed - keys may need to be re-entered".to_string())
+}
+
+/// Encrypt a UTF-8 string and base64-encode the sealed blob.
+fn encrypt(plaintext: &str, key: &[u8; 32]) -> Result<String, String> {
+    Ok(BASE64.encode(seal(plaintext.as_bytes(), key)?))
+}
 
+/// Decrypt a base64-encoded sealed blob back into a UTF-8 string.
+fn decrypt(encrypted: &str, key: &[u8; 32]) -> Result<String, String> {
+    let combined = BASE64.decode(encrypted).map_err(|e| e.to_string())?;
+    let plaintext = unseal(&combined, key)?;
     String::from_utf8(plaintext).map_err(|e| e.to_string())
 }
 
-/// Get the secrets file path in the app data directory
-fn get_secrets_path() -> PathBuf {
-    let mut path = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
-    path.push("com.revelio.app");
-    if !path.exists() {
-        let _ = fs::create_dir_all(&path);
-    }
-    path.push("secrets.enc");
-    path
+/// Encrypt arbitrary bytes under the current session key, for vault files at rest.
+pub fn encrypt_bytes(plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let key = session_key()?;
+    seal(plaintext, &key)
+}
+
+/// Decrypt bytes sealed by `encrypt_bytes` under the current session key.
+pub fn decrypt_bytes(data: &[u8]) -> Result<Vec<u8>, String> {
+    let key = session_key()?;
+    unseal(data, &key)
+}
+
+/// The current session key, for subsystems that seal many blobs at once.
+pub fn current_session_key() -> Result<[u8; 32], String> {
+    session_key()
+}
+
+/// Derive a key from a passphrase and salt (e.g. when restoring a snapshot).
+pub fn derive_key_from(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], String> {
+    derive_key(passphrase, salt)
+}
+
+/// Seal bytes under an explicit key (nonce-prepended AES-256-GCM).
+pub fn seal_with_key(plaintext: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, String> {
+    seal(plaintext, key)
+}
+
+/// Open bytes sealed by `seal_with_key` under an explicit key.
+pub fn open_with_key(data: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, String> {
+    unseal(data, key)
+}
+
+/// Path to the active vault's encrypted secrets store (`.revelio/secrets.enc`),
+/// so credentials stay scoped to the vault that owns them.
+fn secrets_path() -> Result<PathBuf, String> {
+    Ok(active_vault()?.config_dir().join("secrets.enc"))
 }
 
 /// Read and decrypt secrets from file
-fn read_secrets() -> HashMap<String, String> {
-    let path = get_secrets_path();
-    if path.exists() {
-        if let Ok(encrypted) = fs::read_to_string(&path) {
-            if let Ok(decrypted) = decrypt(&encrypted) {
-                return serde_json::from_str(&decrypted).unwrap_or_default();
-            } else {
-                // If decryption fails, the key derivation changed - clear old secrets
-                println!("Warning: Could not decrypt secrets file, may need to re-enter API keys");
-            }
-        }
+fn read_secrets(key: &[u8; 32]) -> Result<HashMap<String, String>, String> {
+    let path = secrets_path()?;
+    if !path.exists() {
+        return Ok(HashMap::new());
     }
-    HashMap::new()
+    let encrypted = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let decrypted = decrypt(&encrypted, key)?;
+    serde_json::from_str(&decrypted).map_err(|e| e.to_string())
 }
 
 /// Encrypt and write secrets to file
-fn write_secrets(secrets: &HashMap<String, String>) -> Result<(), String> {
-    let path = get_secrets_path();
+fn write_secrets(secrets: &HashMap<String, String>, key: &[u8; 32]) -> Result<(), String> {
+    let path = secrets_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
     let json = serde_json::to_string_pretty(secrets).map_err(|e| e.to_string())?;
-    let encrypted = encrypt(&json)?;
+    let encrypted = encrypt(&json, key)?;
     fs::write(&path, encrypted).map_err(|e| e.to_string())
 }
 
-/// Store an API key
+/// Add `key_name` to the active vault's key registry if not already present.
+fn register_key_name(key_name: &str) -> Result<(), String> {
+    let vault = active_vault()?;
+    let mut config = vault.load_config()?;
+    if !config.key_names.iter().any(|n| n == key_name) {
+        config.key_names.push(key_name.to_string());
+        vault.save_config(&config)?;
+    }
+    Ok(())
+}
+
+/// Remove `key_name` from the active vault's key registry.
+fn deregister_key_name(key_name: &str) -> Result<(), String> {
+    let vault = active_vault()?;
+    let mut config = vault.load_config()?;
+    let before = config.key_names.len();
+    config.key_names.retain(|n| n != key_name);
+    if config.key_names.len() != before {
+        vault.save_config(&config)?;
+    }
+    Ok(())
+}
+
+/// Store an API key, registering its name with the active vault.
 pub fn store_api_key(key_name: &str, key_value: &str) -> Result<(), String> {
-    let mut secrets = read_secrets();
+    let key = session_key()?;
+    let mut secrets = read_secrets(&key)?;
     secrets.insert(key_name.to_string(), key_value.to_string());
-    write_secrets(&secrets)
+    write_secrets(&secrets, &key)?;
+    register_key_name(key_name)
 }
 
 /// Retrieve an API key
 pub fn retrieve_api_key(key_name: &str) -> Result<Option<String>, String> {
-    let secrets = read_secrets();
+    let key = session_key()?;
+    let secrets = read_secrets(&key)?;
     Ok(secrets.get(key_name).cloned())
 }
 
-/// Delete an API key
+/// Retrieve every stored secret for the active vault, decrypted.
+pub fn retrieve_all() -> Result<HashMap<String, String>, String> {
+    let key = session_key()?;
+    read_secrets(&key)
+}
+
+/// Delete an API key and drop it from the active vault's registry.
 pub fn delete_api_key(key_name: &str) -> Result<(), String> {
-    let mut secrets = read_secrets();
+    let key = session_key()?;
+    let mut secrets = read_secrets(&key)?;
     secrets.remove(key_name);
-    write_secrets(&secrets)
+    write_secrets(&secrets, &key)?;
+    deregister_key_name(key_name)
 }
 
-/// Get all stored API key names (for UI display)
-pub fn get_stored_key_names() -> Vec<&'static str> {
-    vec!["google_api_key", "tavily_api_key", "fal_api_key"]
+/// Get the key names declared by the active vault (for UI display).
+pub fn get_stored_key_names() -> Vec<String> {
+    active_vault()
+        .and_then(|v| v.load_config())
+        .map(|c| c.key_names)
+        .unwrap_or_default()
 }
 
 /// Check if an API key exists
@@ -37,6 +37,9 @@ fn main() {
         .manage(backend_manager)
         .invoke_handler(tauri::generate_handler![
             // API Key commands
+            commands::unlock_vault,
+            commands::lock_vault,
+            commands::change_passphrase,
             commands::set_api_key,
             commands::get_api_key,
             commands::delete_api_key,
@@ -45,6 +48,7 @@ fn main() {
             commands::start_backend,
             commands::stop_backend,
             commands::get_backend_status,
+            commands::get_backend_health,
             commands::get_backend_port,
             commands::restart_backend,
             // App data commands
@@ -60,6 +64,11 @@ fn main() {
             commands::get_vault_history,
             commands::add_vault_to_history,
             commands::remove_vault_from_history,
+            commands::write_vault_file,
+            commands::read_vault_file,
+            commands::encrypt_existing_vault,
+            commands::create_vault_snapshot,
+            commands::restore_vault_snapshot,
         ])
         .setup(|app| {
             println!("Narrativ is starting...");
@@ -71,9 +80,17 @@ fn main() {
 
             let backend_manager = app.state::<Mutex<BackendManager>>();
             if let Ok(manager) = backend_manager.lock() {
-                match manager.start(&backend_path) {
-                    Ok(_) => println!("Backend started successfully on port 8000"),
-                    Err(e) => println!("Failed to start backend: {}", e),
+                manager.set_app_handle(app.handle());
+                // The backend needs the vault's secrets, which only exist once
+                // the user has unlocked it. Defer the spawn to `unlock_vault`
+                // when booting into a locked vault.
+                if keychain::is_unlocked() {
+                    match manager.start(&backend_path) {
+                        Ok(_) => println!("Backend started successfully on port {}", manager.get_port()),
+                        Err(e) => println!("Failed to start backend: {}", e),
+                    }
+                } else {
+                    println!("Vault is locked; backend will start after unlock");
                 }
             }
 
@@ -2,13 +2,42 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use serde::{Deserialize, Serialize};
 
+pub mod snapshot;
+
 const REVELIO_CONFIG_DIR: &str = ".revelio";
 const REVELIO_CONFIG_FILE: &str = "config.json";
 
+/// Known-plaintext sealed under the derived key so a wrong passphrase can be
+/// rejected before any secret is touched.
+pub const VERIFIER_PLAINTEXT: &str = "revelio-vault-verifier-v1";
+
+/// Provider keys a freshly created vault declares out of the box.
+fn default_key_names() -> Vec<String> {
+    vec![
+        "google_api_key".to_string(),
+        "tavily_api_key".to_string(),
+        "fal_api_key".to_string(),
+    ]
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VaultConfig {
     pub version: String,
     pub created_at: String,
+    /// Base64-encoded Argon2id salt for the master-passphrase KDF.
+    #[serde(default)]
+    pub kdf_salt: Option<String>,
+    /// Base64 AES-256-GCM encryption of `VERIFIER_PLAINTEXT` under the derived
+    /// key, used to validate a passphrase on unlock.
+    #[serde(default)]
+    pub verifier: Option<String>,
+    /// Whether vault content (research/, attachments/, styles/) is stored
+    /// encrypted at rest via `write_encrypted`/`read_encrypted`.
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Provider API key names this vault stores, editable from the UI.
+    #[serde(default = "default_key_names")]
+    pub key_names: Vec<String>,
 }
 
 pub struct Vault {
@@ -41,19 +70,18 @@ impl Vault {
         let config = VaultConfig {
             version: "1.0.0".to_string(),
             created_at: chrono::Utc::now().to_rfc3339(),
+            kdf_salt: None,
+            verifier: None,
+            encrypted: true,
+            key_names: default_key_names(),
         };
 
-        let config_path = vault.config_dir().join(REVELIO_CONFIG_FILE);
-        let config_json = serde_json::to_string_pretty(&config)
-            .map_err(|e| format!("Failed to serialize config: {}", e))?;
-        fs::write(&config_path, config_json)
-            .map_err(|e| format!("Failed to write config file: {}", e))?;
+        vault.save_config(&config)?;
 
         Ok(vault)
     }
 
     /// Open an existing vault
-    #[allow(dead_code)]
     pub fn open(path: &Path) -> Result<Self, String> {
         if !Self::is_valid_vault(path) {
             return Err("Not a valid Revelio vault".to_string());
@@ -61,6 +89,24 @@ impl Vault {
         Ok(Self { path: path.to_path_buf() })
     }
 
+    /// Load the vault's config from disk
+    pub fn load_config(&self) -> Result<VaultConfig, String> {
+        let config_path = self.config_dir().join(REVELIO_CONFIG_FILE);
+        let content = fs::read_to_string(&config_path)
+            .map_err(|e| format!("Failed to read config file: {}", e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse config file: {}", e))
+    }
+
+    /// Persist the vault's config to disk
+    pub fn save_config(&self, config: &VaultConfig) -> Result<(), String> {
+        let config_path = self.config_dir().join(REVELIO_CONFIG_FILE);
+        let config_json = serde_json::to_string_pretty(config)
+            .map_err(|e| format!("Failed to serialize config: {}", e))?;
+        fs::write(&config_path, config_json)
+            .map_err(|e| format!("Failed to write config file: {}", e))
+    }
+
     /// Check if a path is a valid Revelio vault
     pub fn is_valid_vault(path: &Path) -> bool {
         let config_path = path.join(REVELIO_CONFIG_DIR).join(REVELIO_CONFIG_FILE);
@@ -86,6 +132,104 @@ impl Vault {
     pub fn styles_dir(&self) -> PathBuf {
         self.path.join("styles")
     }
+
+    /// Resolve a vault-relative path, rejecting attempts to escape the vault.
+    fn resolve(&self, rel_path: &str) -> Result<PathBuf, String> {
+        let rel = Path::new(rel_path);
+        if rel.is_absolute() || rel.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+            return Err(format!("Invalid vault path: {}", rel_path));
+        }
+        Ok(self.path.join(rel))
+    }
+
+    /// Encrypt `bytes` under the session key and write them to `rel_path`.
+    pub fn write_encrypted(&self, rel_path: &str, bytes: &[u8]) -> Result<(), String> {
+        let target = self.resolve(rel_path)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directory: {}", e))?;
+        }
+        let sealed = crate::keychain::encrypt_bytes(bytes)?;
+        fs::write(&target, sealed).map_err(|e| format!("Failed to write vault file: {}", e))
+    }
+
+    /// Read `rel_path` and decrypt it under the session key.
+    pub fn read_encrypted(&self, rel_path: &str) -> Result<Vec<u8>, String> {
+        let target = self.resolve(rel_path)?;
+        let sealed = fs::read(&target).map_err(|e| format!("Failed to read vault file: {}", e))?;
+        crate::keychain::decrypt_bytes(&sealed)
+    }
+
+    /// Collect every content file under research/, attachments/ and styles/.
+    pub fn content_files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for dir in [self.research_dir(), self.attachments_dir(), self.styles_dir()] {
+            collect_files(&dir, &mut files);
+        }
+        files
+    }
+
+    /// Migrate a legacy plaintext vault: encrypt every file under research/,
+    /// attachments/ and styles/ in place, then mark the vault as encrypted.
+    ///
+    /// No-op on a vault already flagged `encrypted`, and each file that already
+    /// opens under the session key is left alone, so a stray re-run can never
+    /// double-seal content (which `read_encrypted`'s single AEAD open could not
+    /// recover).
+    pub fn encrypt_existing(&self) -> Result<(), String> {
+        let mut config = self.load_config()?;
+        if config.encrypted {
+            return Ok(());
+        }
+        for dir in [self.research_dir(), self.attachments_dir(), self.styles_dir()] {
+            encrypt_tree(&dir)?;
+        }
+        config.encrypted = true;
+        self.save_config(&config)
+    }
+}
+
+/// Recursively collect every regular file under `dir` into `out`.
+fn collect_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    if !dir.exists() {
+        return;
+    }
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, out);
+        } else {
+            out.push(path);
+        }
+    }
+}
+
+/// Recursively encrypt every regular file under `dir` in place.
+fn encrypt_tree(dir: &Path) -> Result<(), String> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.is_dir() {
+            encrypt_tree(&path)?;
+        } else {
+            let plain = fs::read(&path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+            // Already sealed under the session key? Leave it as-is so a re-run
+            // doesn't double-encrypt.
+            if crate::keychain::decrypt_bytes(&plain).is_ok() {
+                continue;
+            }
+            let sealed = crate::keychain::encrypt_bytes(&plain)?;
+            fs::write(&path, sealed)
+                .map_err(|e| format!("Failed to rewrite {:?}: {}", path, e))?;
+        }
+    }
+    Ok(())
 }
 
 /// Get the app settings file path
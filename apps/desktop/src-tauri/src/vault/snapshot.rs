@@ -0,0 +1,239 @@
+//! Encrypted, portable vault snapshots.
+//!
+//! A snapshot serializes the whole vault tree into a single framed stream
+//! (path, length, bytes per entry), splits it into fixed-size chunks, and
+//! AES-256-GCM-encrypts each chunk under the vault's derived key with a
+//! per-chunk nonce. A plaintext manifest at the head records the vault version,
+//! KDF salt, chunk sizes and per-chunk digests so a restore can re-derive the
+//! key from a passphrase and verify integrity before writing anything.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::keychain;
+use super::Vault;
+
+const MAGIC: &[u8; 8] = b"NRVSNAP1";
+const CHUNK_SIZE: usize = 1024 * 1024; // 1 MiB of framed stream per chunk
+
+/// A single file captured in the snapshot.
+#[derive(Debug, Serialize, Deserialize)]
+struct FileEntry {
+    /// Vault-relative path, using forward slashes.
+    path: String,
+    len: u64,
+}
+
+/// Metadata for one encrypted chunk of the framed stream.
+#[derive(Debug, Serialize, Deserialize)]
+struct ChunkMeta {
+    /// Length of the sealed (nonce + ciphertext) chunk on disk.
+    len: u64,
+    /// Base64 SHA-256 digest of the chunk's plaintext, verified on restore.
+    digest: String,
+}
+
+/// The snapshot manifest, written in the clear at the head of the archive.
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    vault_version: String,
+    kdf_salt: String,
+    chunk_size: u64,
+    files: Vec<FileEntry>,
+    chunks: Vec<ChunkMeta>,
+}
+
+/// Create an encrypted snapshot of `vault` at `dest`.
+pub fn create(vault: &Vault, dest: &Path) -> Result<(), String> {
+    let config = vault.load_config()?;
+    let kdf_salt = config
+        .kdf_salt
+        .clone()
+        .ok_or_else(|| "Vault has no passphrase set".to_string())?;
+    let key = keychain::current_session_key()?;
+
+    // Frame the tree: [u32 path_len][path][u64 content_len][content] per file.
+    let mut files = Vec::new();
+    let mut stream: Vec<u8> = Vec::new();
+    for abs in collect_files(&vault.path) {
+        let rel = abs
+            .strip_prefix(&vault.path)
+            .map_err(|e| e.to_string())?
+            .to_string_lossy()
+            .replace('\\', "/");
+        let contents = fs::read(&abs).map_err(|e| format!("Failed to read {:?}: {}", abs, e))?;
+
+        stream.extend_from_slice(&(rel.len() as u32).to_le_bytes());
+        stream.extend_from_slice(rel.as_bytes());
+        stream.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+        stream.extend_from_slice(&contents);
+
+        files.push(FileEntry {
+            path: rel,
+            len: contents.len() as u64,
+        });
+    }
+
+    // Chunk, digest and seal each slice of the stream.
+    let mut chunks = Vec::new();
+    let mut sealed_chunks: Vec<u8> = Vec::new();
+    for slice in stream.chunks(CHUNK_SIZE) {
+        let digest = BASE64.encode(Sha256::digest(slice));
+        let sealed = keychain::seal_with_key(slice, &key)?;
+        chunks.push(ChunkMeta {
+            len: sealed.len() as u64,
+            digest,
+        });
+        sealed_chunks.extend_from_slice(&sealed);
+    }
+
+    let manifest = Manifest {
+        vault_version: config.version,
+        kdf_salt,
+        chunk_size: CHUNK_SIZE as u64,
+        files,
+        chunks,
+    };
+    let manifest_json = serde_json::to_vec(&manifest).map_err(|e| e.to_string())?;
+
+    // Archive: MAGIC | u32 manifest_len | manifest | sealed chunks.
+    let mut archive = Vec::with_capacity(manifest_json.len() + sealed_chunks.len() + 12);
+    archive.extend_from_slice(MAGIC);
+    archive.extend_from_slice(&(manifest_json.len() as u32).to_le_bytes());
+    archive.extend_from_slice(&manifest_json);
+    archive.extend_from_slice(&sealed_chunks);
+
+    fs::write(dest, archive).map_err(|e| format!("Failed to write snapshot: {}", e))
+}
+
+/// Restore a snapshot at `archive_path` into `dest`, re-deriving the key from
+/// `passphrase` and the manifest salt. Refuses a non-empty `dest` unless `force`.
+pub fn restore(
+    archive_path: &Path,
+    dest: &Path,
+    passphrase: &str,
+    force: bool,
+) -> Result<(), String> {
+    if dest.exists() && dir_not_empty(dest) && !force {
+        return Err("Destination is not empty (pass force to overwrite)".to_string());
+    }
+
+    let archive = fs::read(archive_path).map_err(|e| format!("Failed to read snapshot: {}", e))?;
+    if archive.len() < 12 || &archive[..8] != MAGIC {
+        return Err("Not a Narrativ snapshot".to_string());
+    }
+
+    let manifest_len = u32::from_le_bytes([archive[8], archive[9], archive[10], archive[11]]) as usize;
+    let manifest_start = 12;
+    let manifest_end = manifest_start
+        .checked_add(manifest_len)
+        .filter(|&end| end <= archive.len())
+        .ok_or_else(|| "corrupt snapshot".to_string())?;
+    let manifest: Manifest = serde_json::from_slice(&archive[manifest_start..manifest_end])
+        .map_err(|_| "corrupt snapshot".to_string())?;
+
+    let salt = BASE64
+        .decode(&manifest.kdf_salt)
+        .map_err(|_| "corrupt snapshot".to_string())?;
+    let key = keychain::derive_key_from(passphrase, &salt)?;
+
+    // Decrypt and verify every chunk before touching the destination.
+    let mut stream: Vec<u8> = Vec::new();
+    let mut offset = manifest_end;
+    for chunk in &manifest.chunks {
+        let len = chunk.len as usize;
+        let end = offset
+            .checked_add(len)
+            .filter(|&end| end <= archive.len())
+            .ok_or_else(|| "corrupt snapshot".to_string())?;
+        let plain = keychain::open_with_key(&archive[offset..end], &key)?;
+        let digest = BASE64.encode(Sha256::digest(&plain));
+        if digest != chunk.digest {
+            return Err("Snapshot chunk digest mismatch".to_string());
+        }
+        stream.extend_from_slice(&plain);
+        offset = end;
+    }
+
+    // Unframe and write each file.
+    fs::create_dir_all(dest).map_err(|e| format!("Failed to create destination: {}", e))?;
+    let mut cursor = 0usize;
+    while cursor < stream.len() {
+        let path_len = read_u32(&stream, &mut cursor)? as usize;
+        let path = read_bytes(&stream, &mut cursor, path_len)?;
+        let path = String::from_utf8(path).map_err(|_| "corrupt snapshot".to_string())?;
+        let content_len = read_u64(&stream, &mut cursor)? as usize;
+        let content = read_bytes(&stream, &mut cursor, content_len)?;
+
+        // The entry path comes from an untrusted archive; reject anything that
+        // would escape `dest`, mirroring `Vault::resolve`'s write guard.
+        let rel = Path::new(&path);
+        if rel.is_absolute()
+            || rel.components().any(|c| matches!(c, std::path::Component::ParentDir))
+        {
+            return Err(format!("Snapshot contains an unsafe path: {}", path));
+        }
+
+        let target = dest.join(rel);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        fs::write(&target, content).map_err(|e| format!("Failed to write {:?}: {}", target, e))?;
+    }
+
+    Ok(())
+}
+
+/// Recursively collect every regular file under `root`.
+fn collect_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    walk(root, &mut files);
+    files
+}
+
+fn walk(dir: &Path, out: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk(&path, out);
+        } else {
+            out.push(path);
+        }
+    }
+}
+
+fn dir_not_empty(dir: &Path) -> bool {
+    fs::read_dir(dir)
+        .map(|mut e| e.next().is_some())
+        .unwrap_or(false)
+}
+
+fn read_bytes(stream: &[u8], cursor: &mut usize, len: usize) -> Result<Vec<u8>, String> {
+    let end = cursor
+        .checked_add(len)
+        .filter(|&end| end <= stream.len())
+        .ok_or_else(|| "corrupt snapshot".to_string())?;
+    let bytes = stream[*cursor..end].to_vec();
+    *cursor = end;
+    Ok(bytes)
+}
+
+fn read_u32(stream: &[u8], cursor: &mut usize) -> Result<u32, String> {
+    let bytes = read_bytes(stream, cursor, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(stream: &[u8], cursor: &mut usize) -> Result<u64, String> {
+    let bytes = read_bytes(stream, cursor, 8)?;
+    let mut arr = [0u8; 8];
+    arr.copy_from_slice(&bytes);
+    Ok(u64::from_le_bytes(arr))
+}